@@ -1,34 +1,993 @@
+use std::cmp::Reverse;
+use std::env;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::num::IntErrorKind;
+use std::process;
+use std::str::FromStr;
 
-const DIAL_SIZE: i64 = 100;
+#[derive(Debug, Clone, Copy)]
+struct Dial {
+    size: i64,
+    start: i64,
+    target: i64,
+}
+
+impl Default for Dial {
+    fn default() -> Self {
+        Dial {
+            size: 100,
+            start: 50,
+            target: 0,
+        }
+    }
+}
+
+impl Dial {
+    // Sums are widened to i128 so a turn of any i64 length is exact; the
+    // results always fit back into an i64.
+    fn advance(&self, position: i64, delta: i64) -> i64 {
+        (i128::from(position) + i128::from(delta)).rem_euclid(i128::from(self.size)) as i64
+    }
+
+    fn full_turns(&self, position: i64, delta: i64) -> i64 {
+        (i128::from(position) + i128::from(delta)).div_euclid(i128::from(self.size)) as i64
+    }
+
+    // Positions clicked through by a turn, as a number of full turns plus
+    // a leftover arc `from..from + len` that may wrap past zero.
+    fn sweep(&self, position: i64, delta: i64) -> (u64, i64, i64) {
+        let size = self.size as u64;
+        let clicks = delta.unsigned_abs();
+        let rest = (clicks % size) as i64;
+        let from = if delta >= 0 {
+            (position + 1) % self.size
+        } else {
+            (position - rest).rem_euclid(self.size)
+        };
+        (clicks / size, from, rest)
+    }
+
+    // The arc `from..from + len` split into at most two half-open segments
+    // that don't wrap past zero. The end is widened like in `advance`, as
+    // it can run past i64 on a dial that big.
+    fn arc_segments(&self, from: i64, len: i64) -> impl Iterator<Item = (i64, i64)> {
+        let size = i128::from(self.size);
+        let end = i128::from(from) + i128::from(len);
+        let wrapped = (end > size).then_some((0, (end - size) as i64));
+        [(from, end.min(size) as i64)]
+            .into_iter()
+            .chain(wrapped)
+            .filter(|(a, b)| a < b)
+    }
+
+    fn crossings(&self, position: i64, delta: i64) -> i64 {
+        self.hits(position, delta, self.target)
+    }
+
+    // Clicks that land on `mark` while turning `delta` away from
+    // `position`. The starting click is never counted, the final one is.
+    fn hits(&self, position: i64, delta: i64, mark: i64) -> i64 {
+        let below = |x: i128| (x - i128::from(mark)).div_euclid(i128::from(self.size));
+        let (position, delta) = (i128::from(position), i128::from(delta));
+        let crossings = if delta >= 0 {
+            below(position + delta) - below(position)
+        } else {
+            below(position - 1) - below(position + delta - 1)
+        };
+        crossings as i64
+    }
+
+    // Slow click-by-click version of `advance` and `crossings`, used to
+    // check the closed form.
+    fn reference_turn(&self, position: i64, delta: i64) -> (i64, i64) {
+        let step = delta.signum();
+        let mut position = position;
+        let mut crossings = 0;
+        for _ in 0..delta.unsigned_abs() {
+            position = (position + step).rem_euclid(self.size);
+            if position == self.target {
+                crossings += 1;
+            }
+        }
+        (position, crossings)
+    }
+
+    // Shortest turn list from the start position to `end` that hits the
+    // target exactly `hits` times. Each extra full turn adds exactly one
+    // hit, and the only shape that can't be reached is ending on the target
+    // without ever hitting it. Turns in one direction add up, so the answer
+    // is a single run of clicks, split into maximal turns when it doesn't
+    // fit in one.
+    fn synthesize(&self, end: i64, hits: i64) -> Option<Vec<i64>> {
+        if end == self.start && hits == 0 {
+            return Some(Vec::new());
+        }
+        let (sign, mut clicks) = [1, -1]
+            .into_iter()
+            .filter_map(|sign| {
+                let base = (sign * (end - self.start)).rem_euclid(self.size);
+                let extra_turns = hits - self.crossings(self.start, sign * base);
+                (extra_turns >= 0).then(|| {
+                    let clicks = i128::from(extra_turns) * i128::from(self.size);
+                    (sign, clicks + i128::from(base))
+                })
+            })
+            .min_by_key(|&(_, clicks)| clicks)?;
+        let mut turns = Vec::new();
+        while clicks > 0 {
+            let step = clicks.min(i128::from(i64::MAX));
+            turns.push(sign * step as i64);
+            clicks -= step;
+        }
+        Some(turns)
+    }
+}
+
+fn format_turn(delta: i64) -> String {
+    if delta < 0 {
+        format!("L{}", delta.unsigned_abs())
+    } else {
+        format!("R{}", delta)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Move {
+    dial: usize,
+    start: i64,
+    delta: i64,
+    end: i64,
+    crossings: i64,
+}
+
+// Dials geared like an odometer: every full turn of one dial past its
+// zero mark drives the next dial `ratio` clicks in the same direction.
+#[derive(Debug)]
+struct Lock {
+    dial: Dial,
+    ratio: i64,
+    positions: Vec<i64>,
+}
+
+impl Lock {
+    fn new(dial: Dial, dials: usize, ratio: i64) -> Self {
+        Lock {
+            dial,
+            ratio,
+            positions: vec![dial.start; dials],
+        }
+    }
+
+    fn turn(&mut self, index: usize, delta: i64) -> Result<Vec<Move>, TurnErrorKind> {
+        let mut moves = Vec::new();
+        let mut delta = delta;
+        for i in index..self.positions.len() {
+            let start = self.positions[i];
+            let end = self.dial.advance(start, delta);
+            moves.push(Move {
+                dial: i,
+                start,
+                delta,
+                end,
+                crossings: self.dial.crossings(start, delta),
+            });
+            self.positions[i] = end;
+            delta = self
+                .dial
+                .full_turns(start, delta)
+                .checked_mul(self.ratio)
+                .ok_or(TurnErrorKind::GearOverflow)?;
+            if delta == 0 {
+                break;
+            }
+        }
+        Ok(moves)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TraceFormat {
+    Csv,
+    JsonLines,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(TraceFormat::Csv),
+            "jsonl" => Ok(TraceFormat::JsonLines),
+            _ => Err(format!("Unknown trace format {}", s)),
+        }
+    }
+}
 
-fn parse_turn(s: &str) -> i64 {
-    let (dir, num) = s.split_at(1);
-    let sign = match dir {
-        "L" => -1,
-        "R" => 1,
-        _ => panic!("Bad input direction"),
+fn open_output(path: Option<&str>) -> Result<Box<dyn Write>, io::Error> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    })
+}
+
+struct Trace {
+    format: TraceFormat,
+    out: Box<dyn Write>,
+}
+
+impl Trace {
+    fn new(format: TraceFormat, path: Option<&str>) -> Result<Self, io::Error> {
+        let mut trace = Trace {
+            format,
+            out: open_output(path)?,
+        };
+        if let TraceFormat::Csv = format {
+            writeln!(trace.out, "line,turn,dial,start,end,crossings,total")?;
+        }
+        Ok(trace)
+    }
+
+    fn record(&mut self, line: usize, turn: &str, m: &Move, total: i64) -> Result<(), io::Error> {
+        match self.format {
+            TraceFormat::Csv => writeln!(
+                self.out,
+                "{},{},{},{},{},{},{}",
+                line,
+                turn,
+                m.dial + 1,
+                m.start,
+                m.end,
+                m.crossings,
+                total
+            ),
+            TraceFormat::JsonLines => writeln!(
+                self.out,
+                "{{\"line\":{},\"turn\":\"{}\",\"dial\":{},\"start\":{},\"end\":{},\"crossings\":{},\"total\":{}}}",
+                line,
+                turn,
+                m.dial + 1,
+                m.start,
+                m.end,
+                m.crossings,
+                total
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum HeatmapFormat {
+    Table,
+    Ascii,
+    Ppm,
+}
+
+impl FromStr for HeatmapFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(HeatmapFormat::Table),
+            "ascii" => Ok(HeatmapFormat::Ascii),
+            "ppm" => Ok(HeatmapFormat::Ppm),
+            _ => Err(format!("Unknown heatmap format {}", s)),
+        }
+    }
+}
+
+// Per-position click counts. Full turns are tallied once and the leftover
+// arc of each move goes into a difference array, so a move costs O(1)
+// however many clicks it spans.
+struct Heatmap {
+    full_turns: i128,
+    diff: Vec<i128>,
+    landings: Vec<i128>,
+}
+
+impl Heatmap {
+    const BAR_WIDTH: i128 = 60;
+    const IMAGE_HEIGHT: i128 = 64;
+    // One row or pixel column per position, so anything bigger isn't worth
+    // drawing and may not even fit in memory.
+    const MAX_SIZE: i64 = 1 << 24;
+
+    fn new(dial: &Dial) -> Self {
+        let size = dial.size as usize;
+        Heatmap {
+            full_turns: 0,
+            diff: vec![0; size + 1],
+            landings: vec![0; size],
+        }
+    }
+
+    fn record(&mut self, dial: &Dial, m: &Move) {
+        let (full_turns, from, len) = dial.sweep(m.start, m.delta);
+        self.full_turns += i128::from(full_turns);
+        for (a, b) in dial.arc_segments(from, len) {
+            self.diff[a as usize] += 1;
+            self.diff[b as usize] -= 1;
+        }
+        self.landings[m.end as usize] += 1;
+    }
+
+    fn passes(&self) -> Vec<i128> {
+        let mut running = self.full_turns;
+        self.diff[..self.landings.len()]
+            .iter()
+            .map(|d| {
+                running += d;
+                running
+            })
+            .collect()
+    }
+
+    fn write(&self, format: HeatmapFormat, out: &mut dyn Write) -> Result<(), io::Error> {
+        let passes = self.passes();
+        let max = passes.iter().copied().max().unwrap_or(0).max(1);
+        match format {
+            HeatmapFormat::Table => {
+                writeln!(out, "{:>8} {:>12} {:>12}", "position", "clicks", "landings")?;
+                for (p, (clicks, landings)) in passes.iter().zip(&self.landings).enumerate() {
+                    writeln!(out, "{:>8} {:>12} {:>12}", p, clicks, landings)?;
+                }
+            }
+            HeatmapFormat::Ascii => {
+                for (p, clicks) in passes.iter().enumerate() {
+                    let bar = "#".repeat((clicks * Self::BAR_WIDTH / max) as usize);
+                    writeln!(
+                        out,
+                        "{:>6} |{:<width$}| {}",
+                        p,
+                        bar,
+                        clicks,
+                        width = Self::BAR_WIDTH as usize
+                    )?;
+                }
+            }
+            HeatmapFormat::Ppm => {
+                writeln!(out, "P3")?;
+                writeln!(out, "{} {}", passes.len(), Self::IMAGE_HEIGHT)?;
+                writeln!(out, "255")?;
+                for row in (0..Self::IMAGE_HEIGHT).rev() {
+                    for clicks in &passes {
+                        let filled = clicks * Self::IMAGE_HEIGHT / max > row;
+                        let px = if filled { "0 0 0" } else { "255 255 255" };
+                        writeln!(out, "{}", px)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Range-add, point-query Fenwick tree. Adding at or past the end is a no-op,
+// so a range may run up to the last slot.
+struct Fenwick {
+    tree: Vec<i128>,
+}
+
+impl Fenwick {
+    fn new(len: usize) -> Self {
+        Fenwick {
+            tree: vec![0; len + 1],
+        }
+    }
+
+    fn add(&mut self, i: usize, value: i128) {
+        let mut i = i + 1;
+        while i < self.tree.len() {
+            self.tree[i] += value;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    fn add_range(&mut self, from: usize, to: usize, value: i128) {
+        self.add(from, value);
+        self.add(to, -value);
+    }
+
+    fn get(&self, i: usize) -> i128 {
+        let mut i = i + 1;
+        let mut total = 0;
+        while i > 0 {
+            total += self.tree[i];
+            i -= i & i.wrapping_neg();
+        }
+        total
+    }
+}
+
+struct Options {
+    dial: Dial,
+    dials: usize,
+    ratio: i64,
+    trace: Option<TraceFormat>,
+    trace_out: Option<String>,
+    verify: bool,
+    input: String,
+    every: Option<usize>,
+    synth: Option<(i64, i64)>,
+    heatmap: Option<HeatmapFormat>,
+    heatmap_out: Option<String>,
+    what_if: Option<usize>,
+    marks: Vec<i64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            dial: Dial::default(),
+            dials: 1,
+            ratio: 1,
+            trace: None,
+            trace_out: None,
+            verify: false,
+            input: String::from("1-input.txt"),
+            every: None,
+            synth: None,
+            heatmap: None,
+            heatmap_out: None,
+            what_if: None,
+            marks: Vec::new(),
+        }
+    }
+}
+
+fn parse_args() -> Result<Options, Box<dyn Error>> {
+    let mut opts = Options::default();
+    let dial = &mut opts.dial;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--size" => dial.size = value()?.parse()?,
+            "--start" => dial.start = value()?.parse()?,
+            "--target" => dial.target = value()?.parse()?,
+            "--dials" => opts.dials = value()?.parse()?,
+            "--ratio" => opts.ratio = value()?.parse()?,
+            "--trace" => opts.trace = Some(value()?.parse()?),
+            "--trace-out" => opts.trace_out = Some(value()?),
+            "--verify" => opts.verify = true,
+            "--input" => opts.input = value()?,
+            "--every" => opts.every = Some(value()?.parse()?),
+            "--heatmap" => opts.heatmap = Some(value()?.parse()?),
+            "--heatmap-out" => opts.heatmap_out = Some(value()?),
+            "--marks" => {
+                opts.marks = value()?
+                    .split(',')
+                    .map(|m| m.trim().parse())
+                    .collect::<Result<_, _>>()?;
+                opts.marks.sort_unstable();
+                opts.marks.dedup();
+            }
+            "--what-if" => opts.what_if = Some(value()?.parse()?),
+            "--synth" => {
+                let v = value()?;
+                let (end, hits) = v
+                    .split_once(',')
+                    .ok_or_else(|| format!("--synth expects END,HITS, got {}", v))?;
+                opts.synth = Some((end.parse()?, hits.parse()?));
+            }
+            _ => return Err(format!("Unknown argument {}", arg).into()),
+        }
+    }
+    if dial.size <= 0 {
+        return Err("Dial size must be positive".into());
+    }
+    if !(0..dial.size).contains(&dial.start) || !(0..dial.size).contains(&dial.target) {
+        return Err("Start and target must lie on the dial".into());
+    }
+    if opts.marks.iter().any(|m| !(0..dial.size).contains(m)) {
+        return Err("Marks must lie on the dial".into());
+    }
+    if opts.every == Some(0) {
+        return Err("--every needs a positive line count".into());
+    }
+    if opts.trace.is_some()
+        && opts.trace_out.is_none()
+        && opts.heatmap.is_some()
+        && opts.heatmap_out.is_none()
+    {
+        return Err("Trace and heatmap can't both go to stdout".into());
+    }
+    if opts.heatmap.is_some() && dial.size > Heatmap::MAX_SIZE {
+        return Err(format!(
+            "Heatmap needs a dial of at most {} positions",
+            Heatmap::MAX_SIZE
+        )
+        .into());
+    }
+    if opts.dials == 0 {
+        return Err("A lock needs at least one dial".into());
+    }
+    if opts.synth.is_some() {
+        let defaults = Options::default();
+        if opts.dials != defaults.dials
+            || opts.ratio != defaults.ratio
+            || opts.input != defaults.input
+            || opts.trace.is_some()
+            || opts.trace_out.is_some()
+            || opts.verify
+            || opts.every.is_some()
+            || !opts.marks.is_empty()
+            || opts.heatmap.is_some()
+            || opts.heatmap_out.is_some()
+            || opts.what_if.is_some()
+        {
+            return Err("--synth only takes --size, --start and --target".into());
+        }
+    }
+    if opts.what_if.is_some() {
+        if opts.dials > 1 {
+            return Err("What-if analysis works on a single dial".into());
+        }
+        if opts.trace.is_some()
+            || opts.verify
+            || opts.every.is_some()
+            || !opts.marks.is_empty()
+            || opts.heatmap.is_some()
+        {
+            return Err(
+                "--what-if can't be combined with --trace, --verify, --every, --marks or --heatmap"
+                    .into(),
+            );
+        }
+    }
+    Ok(opts)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TurnErrorKind {
+    BadDial,
+    NoSuchDial,
+    BadDirection,
+    EmptyCount,
+    BadCount,
+    Overflow,
+    GearOverflow,
+    TotalOverflow,
+}
+
+impl fmt::Display for TurnErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            TurnErrorKind::BadDial => "dial prefix must be a positive number",
+            TurnErrorKind::NoSuchDial => "dial is not on this lock",
+            TurnErrorKind::BadDirection => "direction must be L or R",
+            TurnErrorKind::EmptyCount => "missing click count",
+            TurnErrorKind::BadCount => "click count is not a number",
+            TurnErrorKind::Overflow => "click count is too large",
+            TurnErrorKind::GearOverflow => "geared turn overflows the next dial",
+            TurnErrorKind::TotalOverflow => "zero count overflows",
+        };
+        f.write_str(reason)
+    }
+}
+
+#[derive(Debug)]
+struct TurnError {
+    line: usize,
+    token: String,
+    kind: TurnErrorKind,
+}
+
+impl fmt::Display for TurnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}: bad turn {:?}: {}",
+            self.line, self.token, self.kind
+        )
+    }
+}
+
+impl Error for TurnError {}
+
+#[derive(Debug, Clone, Copy)]
+struct Turn {
+    dial: usize,
+    delta: i64,
+}
+
+fn parse_turn(s: &str) -> Result<Turn, TurnErrorKind> {
+    let (dial, s) = match s.split_once(':') {
+        Some((dial_s, rest)) => match dial_s.parse::<usize>() {
+            Ok(dial) if dial > 0 => (dial - 1, rest),
+            _ => return Err(TurnErrorKind::BadDial),
+        },
+        None => (0, s),
+    };
+    let mut chars = s.chars();
+    let sign = match chars.next() {
+        Some('L') => -1,
+        Some('R') => 1,
+        _ => return Err(TurnErrorKind::BadDirection),
     };
-    sign * num.parse::<i64>().unwrap()
+    let count = chars.as_str().parse::<u64>().map_err(|e| match e.kind() {
+        IntErrorKind::Empty => TurnErrorKind::EmptyCount,
+        IntErrorKind::PosOverflow => TurnErrorKind::Overflow,
+        _ => TurnErrorKind::BadCount,
+    })?;
+    let count = i64::try_from(count).map_err(|_| TurnErrorKind::Overflow)?;
+    Ok(Turn {
+        dial,
+        delta: sign * count,
+    })
 }
 
-fn main() -> Result<(), io::Error> {
-    let f = File::open("1-input.txt")?;
-    let br = BufReader::new(f);
-    let mut position = 50;
-    let mut zeroes = 0;
-    for line in br.lines() {
+fn parse_line(i: usize, line: &str, dials: usize) -> Result<Turn, TurnError> {
+    parse_turn(line)
+        .and_then(|turn| {
+            if turn.dial < dials {
+                Ok(turn)
+            } else {
+                Err(TurnErrorKind::NoSuchDial)
+            }
+        })
+        .map_err(|kind| TurnError {
+            line: i + 1,
+            token: line.to_string(),
+            kind,
+        })
+}
+
+fn synth(dial: &Dial, end: i64, hits: i64) -> Result<(), Box<dyn Error>> {
+    if !(0..dial.size).contains(&end) || hits < 0 {
+        return Err("Synthesis needs an end on the dial and a non-negative hit count".into());
+    }
+    let turns = dial.synthesize(end, hits).ok_or_else(|| {
+        format!(
+            "No rotation sequence ends on {} with {} hits of {}",
+            end, hits, dial.target
+        )
+    })?;
+
+    // Feed the result back through the forward counter as a self-check.
+    let (position, crossings) = turns.iter().fold((dial.start, 0), |(p, c), &t| {
+        (dial.advance(p, t), c + dial.crossings(p, t))
+    });
+    if (position, crossings) != (end, hits) {
+        return Err(format!(
+            "Synthesized turns end on {} with {} hits, expected {} with {}",
+            position, crossings, end, hits
+        )
+        .into());
+    }
+    for &t in &turns {
+        println!("{}", format_turn(t));
+    }
+    Ok(())
+}
+
+// Zero counts with each turn removed or flipped. Prefix sums give the count
+// up to the turn; the turns after it only see their start shifted, which is
+// the same as counting hits of a shifted mark on the original path, so one
+// backwards sweep keeps the hits for every mark in a Fenwick tree. The tree
+// only holds the arc ends and marks that come up, not every dial position.
+fn what_if_counts(dial: &Dial, deltas: &[i64]) -> (i128, Vec<i128>, Vec<i128>) {
+    let mut positions = vec![dial.start];
+    let mut prefix = vec![0i128];
+    for (i, &delta) in deltas.iter().enumerate() {
+        prefix.push(prefix[i] + i128::from(dial.crossings(positions[i], delta)));
+        positions.push(dial.advance(positions[i], delta));
+    }
+    let baseline = prefix[deltas.len()];
+
+    let shifted_mark = |i: usize, from: i64| {
+        let shift = i128::from(from) - i128::from(positions[i + 1]);
+        (i128::from(dial.target) - shift).rem_euclid(i128::from(dial.size)) as i64
+    };
+    let flipped_end = |i: usize| dial.advance(positions[i], -deltas[i]);
+    let sweeps: Vec<_> = (0..deltas.len())
+        .map(|i| dial.sweep(positions[i], deltas[i]))
+        .collect();
+    let mut coords = Vec::new();
+    for (i, &(_, from, len)) in sweeps.iter().enumerate() {
+        coords.extend(dial.arc_segments(from, len).flat_map(|(a, b)| [a, b]));
+        coords.push(shifted_mark(i, positions[i]));
+        coords.push(shifted_mark(i, flipped_end(i)));
+    }
+    coords.sort_unstable();
+    coords.dedup();
+    let index = |x: i64| coords.binary_search(&x).unwrap();
+
+    let mut removed = vec![0; deltas.len()];
+    let mut flipped = vec![0; deltas.len()];
+    let mut full_turns = 0i128;
+    let mut suffix = Fenwick::new(coords.len());
+    for (i, &delta) in deltas.iter().enumerate().rev() {
+        let after = |from: i64| full_turns + suffix.get(index(shifted_mark(i, from)));
+        removed[i] = prefix[i] + after(positions[i]);
+        flipped[i] =
+            prefix[i] + i128::from(dial.crossings(positions[i], -delta)) + after(flipped_end(i));
+
+        let (turns, from, len) = sweeps[i];
+        full_turns += i128::from(turns);
+        for (a, b) in dial.arc_segments(from, len) {
+            suffix.add_range(index(a), index(b), 1);
+        }
+    }
+    (baseline, removed, flipped)
+}
+
+fn what_if(dial: &Dial, br: Box<dyn BufRead>, top: usize) -> Result<(), Box<dyn Error>> {
+    let mut lines = Vec::new();
+    let mut deltas = Vec::new();
+    for (i, line) in br.lines().enumerate() {
+        let line = line?;
+        deltas.push(parse_line(i, &line, 1)?.delta);
+        lines.push(line);
+    }
+    let (baseline, removed, flipped) = what_if_counts(dial, &deltas);
+
+    println!("Baseline passes of {}: {}", dial.target, baseline);
+    println!(
+        "{:>8} {:<12} {:>12} {:>12}",
+        "line", "turn", "removed", "flipped"
+    );
+    for (i, line) in lines.iter().enumerate() {
+        println!(
+            "{:>8} {:<12} {:>12} {:>12}",
+            i + 1,
+            line,
+            removed[i],
+            flipped[i]
+        );
+    }
+
+    let influence = |i: usize| {
+        (removed[i] - baseline)
+            .abs()
+            .max((flipped[i] - baseline).abs())
+    };
+    let mut ranked: Vec<_> = (0..lines.len()).collect();
+    ranked.sort_by_key(|&i| Reverse(influence(i)));
+    println!("Most influential turns:");
+    for &i in ranked.iter().take(top) {
+        println!(
+            "line {} {}: removed {:+}, flipped {:+}",
+            i + 1,
+            lines[i],
+            removed[i] - baseline,
+            flipped[i] - baseline
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let opts = parse_args()?;
+    let dial = opts.dial;
+    if let Some((end, hits)) = opts.synth {
+        return synth(&dial, end, hits);
+    }
+    let br: Box<dyn BufRead> = if opts.input == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(BufReader::new(File::open(&opts.input)?))
+    };
+    if let Some(top) = opts.what_if {
+        return what_if(&dial, br, top);
+    }
+    let mut trace = opts
+        .trace
+        .map(|format| Trace::new(format, opts.trace_out.as_deref()))
+        .transpose()?;
+
+    // Keep stdout clean for the trace or heatmap when it isn't going to a
+    // file.
+    let stdout_taken = (trace.is_some() && opts.trace_out.is_none())
+        || (opts.heatmap.is_some() && opts.heatmap_out.is_none());
+    let summary = if stdout_taken {
+        |s: String| eprintln!("{}", s)
+    } else {
+        |s: String| println!("{}", s)
+    };
+    let mut lock = Lock::new(dial, opts.dials, opts.ratio);
+    let mut landings = vec![0; opts.dials];
+    let mut zeroes = vec![0i64; opts.dials];
+    let mut moves = 0;
+    // The heatmap follows the first dial, which is the one turns address
+    // by default.
+    let mut heatmap = opts.heatmap.map(|_| Heatmap::new(&dial));
+    let mut mark_landings = vec![vec![0i64; opts.marks.len()]; opts.dials];
+    let mut mark_hits = vec![vec![0i64; opts.marks.len()]; opts.dials];
+    for (i, line) in br.lines().enumerate() {
         let line = line?;
-        let resulting_position = position + parse_turn(&line);
+        let error = |kind| TurnError {
+            line: i + 1,
+            token: line.clone(),
+            kind,
+        };
+        let turn = parse_line(i, &line, opts.dials)?;
 
-        zeroes += (resulting_position / DIAL_SIZE).abs();
-        if position > 0 && resulting_position <= 0 {
-            zeroes += 1;
+        for m in lock.turn(turn.dial, turn.delta).map_err(error)? {
+            if opts.verify {
+                let expected = dial.reference_turn(m.start, m.delta);
+                if expected != (m.end, m.crossings) {
+                    return Err(format!(
+                        "line {}: dial {} turning {} from {} gives (end {}, crossings {}), reference gives (end {}, crossings {})",
+                        i + 1,
+                        m.dial + 1,
+                        m.delta,
+                        m.start,
+                        m.end,
+                        m.crossings,
+                        expected.0,
+                        expected.1
+                    )
+                    .into());
+                }
+                moves += 1;
+            }
+            if let Some(heatmap) = &mut heatmap
+                && m.dial == 0
+            {
+                heatmap.record(&dial, &m);
+            }
+            for (j, &mark) in opts.marks.iter().enumerate() {
+                let hits = &mut mark_hits[m.dial][j];
+                *hits = hits
+                    .checked_add(dial.hits(m.start, m.delta, mark))
+                    .ok_or_else(|| error(TurnErrorKind::TotalOverflow))?;
+                if m.end == mark {
+                    let landings = &mut mark_landings[m.dial][j];
+                    *landings = landings
+                        .checked_add(1)
+                        .ok_or_else(|| error(TurnErrorKind::TotalOverflow))?;
+                }
+            }
+            zeroes[m.dial] = zeroes[m.dial]
+                .checked_add(m.crossings)
+                .ok_or_else(|| error(TurnErrorKind::TotalOverflow))?;
+            if m.end == dial.target {
+                landings[m.dial] += 1;
+            }
+            if let Some(trace) = &mut trace {
+                trace.record(i + 1, &line, &m, zeroes[m.dial])?;
+            }
+        }
+        // Input from stdin may never end, so don't hold trace lines back.
+        if let Some(trace) = &mut trace
+            && opts.input == "-"
+        {
+            trace.out.flush()?;
+        }
+        if opts.every.is_some_and(|n| (i + 1) % n == 0) {
+            let counts: Vec<_> = zeroes.iter().map(i64::to_string).collect();
+            summary(format!(
+                "Line {}: passed {}: {}",
+                i + 1,
+                dial.target,
+                counts.join(" ")
+            ));
+        }
+    }
+    if let Some(trace) = &mut trace {
+        trace.out.flush()?;
+    }
+    if let (Some(heatmap), Some(format)) = (&heatmap, opts.heatmap) {
+        let mut out = open_output(opts.heatmap_out.as_deref())?;
+        heatmap.write(format, &mut out)?;
+        out.flush()?;
+    }
+    for d in 0..opts.dials {
+        let prefix = if opts.dials > 1 {
+            format!("Dial {} ", d + 1)
+        } else {
+            String::new()
+        };
+        summary(format!(
+            "{}Landed on {}: {}",
+            prefix, dial.target, landings[d]
+        ));
+        summary(format!("{}Passed {}: {}", prefix, dial.target, zeroes[d]));
+        if opts.marks.is_empty() {
+            continue;
+        }
+        for (j, mark) in opts.marks.iter().enumerate() {
+            summary(format!(
+                "{}Mark {}: landed {}, passed {}",
+                prefix, mark, mark_landings[d][j], mark_hits[d][j]
+            ));
         }
-        position = resulting_position.rem_euclid(DIAL_SIZE);
-        // println!("Move: {}, (P: {}, C: {})", line, position, zeroes);
+        let combined = |counts: &[i64]| {
+            counts
+                .iter()
+                .try_fold(0i64, |total, &c| total.checked_add(c))
+                .ok_or("Combined mark count overflows")
+        };
+        summary(format!(
+            "{}All marks: landed {}, passed {}",
+            prefix,
+            combined(&mark_landings[d])?,
+            combined(&mark_hits[d])?
+        ));
+    }
+    if opts.verify {
+        summary(format!("Verified against reference: {} moves", moves));
     }
-    println!("Result: {}", zeroes);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Zero count of the whole turn list, the slow way.
+    fn passes(dial: &Dial, deltas: &[i64]) -> i128 {
+        let (_, passes) = deltas.iter().fold((dial.start, 0), |(p, c), &d| {
+            (dial.advance(p, d), c + i128::from(dial.crossings(p, d)))
+        });
+        passes
+    }
+
+    #[test]
+    fn what_if_matches_rerun() {
+        // Small xorshift generator, so the turn lists are the same on every
+        // run.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move |bound: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % bound
+        };
+        // Small dials wrap on most turns; the largest ones make arc ends and
+        // shifted marks run past i64.
+        let sizes = [
+            1,
+            2,
+            3,
+            7,
+            10,
+            100,
+            i64::MAX / 2 + 1,
+            i64::MAX - 1,
+            i64::MAX,
+        ];
+        for size in sizes {
+            for _ in 0..50 {
+                let dial = Dial {
+                    size,
+                    start: next(size as u64) as i64,
+                    target: next(size as u64) as i64,
+                };
+                let limit = (size as u64).saturating_mul(3).min(i64::MAX as u64);
+                let deltas: Vec<i64> = (0..next(20))
+                    .map(|_| {
+                        let clicks = next(limit + 1) as i64;
+                        if next(2) == 0 { clicks } else { -clicks }
+                    })
+                    .collect();
+                let (baseline, removed, flipped) = what_if_counts(&dial, &deltas);
+                assert_eq!(baseline, passes(&dial, &deltas));
+                for i in 0..deltas.len() {
+                    let without = [&deltas[..i], &deltas[i + 1..]].concat();
+                    let mut reversed = deltas.clone();
+                    reversed[i] = -reversed[i];
+                    assert_eq!(
+                        (removed[i], flipped[i]),
+                        (passes(&dial, &without), passes(&dial, &reversed)),
+                        "turn {} of {:?} on {:?}",
+                        i + 1,
+                        deltas,
+                        dial
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,44 +1,701 @@
 use std::{
-    cmp::max,
-    fs::File,
-    io::{self, BufRead, BufReader},
+    cmp::{max, min},
+    env,
+    error::Error,
+    fmt, fs,
+    num::IntErrorKind,
+    process,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
 };
 
-fn parse_range(s: &str) -> (i64, i64) {
-    let (low_s, high_s) = s.split_once('-').unwrap();
-    (low_s.parse().unwrap(), high_s.parse().unwrap())
+// A test for invalid IDs. Every rule can be checked one ID at a time;
+// `tally` and `ids` scan the range with it unless the rule knows a faster
+// way to enumerate its invalid IDs.
+trait Rule: fmt::Display + Sync {
+    fn is_invalid(&self, value: u128, radix: u32) -> bool;
+
+    fn tally(&self, low: u128, high: u128, radix: u32) -> Option<Tally> {
+        scan_tally(self, low, high, radix)
+    }
+
+    fn ids(&self, low: u128, high: u128, radix: u32) -> Vec<u128> {
+        (low..=high)
+            .filter(|x| self.is_invalid(*x, radix))
+            .collect()
+    }
+}
+
+fn scan_tally(rule: &(impl Rule + ?Sized), low: u128, high: u128, radix: u32) -> Option<Tally> {
+    (low..=high)
+        .filter(|x| rule.is_invalid(*x, radix))
+        .try_fold(Tally::default(), |t, x| {
+            t.checked_add(Tally { count: 1, sum: x })
+        })
+}
+
+// IDs made of one block of digits repeated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Repeats {
+    Exactly(u32),
+    AtLeastTwice,
 }
 
-fn is_invalid(value: i64) -> bool {
-    let len = value.ilog10() + 1;
-    for j in (1..len).filter(|x| len % x == 0) {
-        let split = 10_i64.pow(j);
-        let target = value % split;
-        let mut mut_value = value;
-        while mut_value > 0 && (mut_value % split) == target {
-            mut_value /= split;
+impl FromStr for Repeats {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "twice" => Ok(Repeats::Exactly(2)),
+            "at-least-twice" => Ok(Repeats::AtLeastTwice),
+            _ => match s.strip_prefix("exactly:").map(str::parse) {
+                Some(Ok(k)) if k >= 2 => Ok(Repeats::Exactly(k)),
+                _ => Err(format!("Unknown rule {}", s)),
+            },
         }
-        if mut_value == 0 {
-            return true;
+    }
+}
+
+impl fmt::Display for Repeats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Repeats::Exactly(k) => write!(f, "exactly {} repeats", k),
+            Repeats::AtLeastTwice => write!(f, "at least 2 repeats"),
+        }
+    }
+}
+
+impl Rule for Repeats {
+    fn is_invalid(&self, value: u128, radix: u32) -> bool {
+        is_invalid(value, *self, radix)
+    }
+
+    fn tally(&self, low: u128, high: u128, radix: u32) -> Option<Tally> {
+        invalid_tally(low, high, *self, radix)
+    }
+
+    fn ids(&self, low: u128, high: u128, radix: u32) -> Vec<u128> {
+        invalid_ids(low, high, *self, radix)
+    }
+}
+
+fn digits(value: u128, radix: u32) -> Vec<u32> {
+    let mut digits = Vec::new();
+    let mut rest = value;
+    loop {
+        digits.push((rest % u128::from(radix)) as u32);
+        rest /= u128::from(radix);
+        if rest == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    digits
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Palindrome;
+
+impl fmt::Display for Palindrome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "palindrome")
+    }
+}
+
+impl Rule for Palindrome {
+    fn is_invalid(&self, value: u128, radix: u32) -> bool {
+        let digits = digits(value, radix);
+        digits.iter().eq(digits.iter().rev())
+    }
+}
+
+// IDs containing a run of at least this many equal digits.
+#[derive(Debug, Clone, Copy)]
+struct DigitRun(usize);
+
+impl fmt::Display for DigitRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "digit run of {} or more", self.0)
+    }
+}
+
+impl Rule for DigitRun {
+    fn is_invalid(&self, value: u128, radix: u32) -> bool {
+        digits(value, radix)
+            .chunk_by(|a, b| a == b)
+            .any(|run| run.len() >= self.0)
+    }
+}
+
+fn parse_rule(s: &str) -> Result<Box<dyn Rule>, String> {
+    if s == "palindrome" {
+        return Ok(Box::new(Palindrome));
+    }
+    if let Some(k) = s.strip_prefix("run:") {
+        return match k.parse() {
+            Ok(k) if k >= 2 => Ok(Box::new(DigitRun(k))),
+            _ => Err(format!("Unknown rule {}", s)),
+        };
+    }
+    Ok(Box::new(s.parse::<Repeats>()?))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeErrorKind {
+    Empty,
+    MissingDash,
+    MissingBound,
+    BadDigit,
+    Overflow,
+    Reversed,
+}
+
+impl fmt::Display for RangeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            RangeErrorKind::Empty => "empty range between commas",
+            RangeErrorKind::MissingDash => "expected LOW-HIGH",
+            RangeErrorKind::MissingBound => "missing low or high end",
+            RangeErrorKind::BadDigit => "not a number in this radix",
+            RangeErrorKind::Overflow => "ID does not fit in 128 bits",
+            RangeErrorKind::Reversed => "high end is below low end",
+        };
+        f.write_str(reason)
+    }
+}
+
+#[derive(Debug)]
+struct RangeError {
+    line: usize,
+    column: usize,
+    token: String,
+    kind: RangeErrorKind,
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: bad range {:?}: {}",
+            self.line, self.column, self.token, self.kind
+        )
+    }
+}
+
+impl Error for RangeError {}
+
+// Literals may carry a 0x, 0o or 0b prefix in either case, unless that
+// letter is itself a digit in the default radix (0b1 is a plain number in
+// hexadecimal). IDs are unsigned, so a sign is a bad digit even where
+// `from_str_radix` would take it.
+fn parse_id(s: &str, radix: u32) -> Result<u128, RangeErrorKind> {
+    let s = s.trim();
+    let prefixed = [("0x", 16), ("0o", 8), ("0b", 2)]
+        .into_iter()
+        .find(|(prefix, _)| {
+            s.get(..prefix.len())
+                .is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+                && !prefix[1..].chars().all(|c| c.is_digit(radix))
+        });
+    let (digits, radix) = match prefixed {
+        Some((prefix, prefix_radix)) => (&s[prefix.len()..], prefix_radix),
+        None => (s, radix),
+    };
+    if digits.starts_with(['+', '-']) {
+        return Err(RangeErrorKind::BadDigit);
+    }
+    u128::from_str_radix(digits, radix).map_err(|e| match e.kind() {
+        IntErrorKind::Empty => RangeErrorKind::MissingBound,
+        IntErrorKind::PosOverflow => RangeErrorKind::Overflow,
+        _ => RangeErrorKind::BadDigit,
+    })
+}
+
+fn parse_range(s: &str, radix: u32) -> Result<(u128, u128), RangeErrorKind> {
+    let (low_s, high_s) = s.split_once('-').ok_or(RangeErrorKind::MissingDash)?;
+    let (low, high) = (parse_id(low_s, radix)?, parse_id(high_s, radix)?);
+    if high < low {
+        return Err(RangeErrorKind::Reversed);
+    }
+    Ok((low, high))
+}
+
+// Comma-separated ranges, possibly spread over several lines. Whitespace
+// around tokens is ignored, and a line may end with a trailing comma.
+fn parse_ranges(input: &str, radix: u32) -> Result<Vec<(u128, u128)>, RangeError> {
+    let mut ranges = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let tokens: Vec<_> = line.split(',').collect();
+        let mut offset = 0;
+        for (j, raw) in tokens.iter().enumerate() {
+            let token = raw.trim();
+            let column = line[..offset + raw.len() - raw.trim_start().len()]
+                .chars()
+                .count()
+                + 1;
+            offset += raw.len() + 1;
+            let range = if token.is_empty() {
+                if j + 1 == tokens.len() {
+                    continue;
+                }
+                Err(RangeErrorKind::Empty)
+            } else {
+                parse_range(token, radix)
+            };
+            ranges.push(range.map_err(|kind| RangeError {
+                line: i + 1,
+                column,
+                token: token.to_string(),
+                kind,
+            })?);
         }
     }
-    false
+    Ok(ranges)
+}
+
+fn format_id(value: u128, radix: u32) -> String {
+    digits(value, radix)
+        .into_iter()
+        .map(|d| char::from_digit(d, radix).unwrap())
+        .collect()
+}
+
+fn digit_count(value: u128, radix: u32) -> u32 {
+    value.checked_ilog(u128::from(radix)).map_or(1, |l| l + 1)
+}
+
+fn repeats_with_period(value: u128, period: u32, radix: u32) -> bool {
+    let split = u128::from(radix).pow(period);
+    let target = value % split;
+    let mut mut_value = value;
+    while mut_value > 0 && (mut_value % split) == target {
+        mut_value /= split;
+    }
+    mut_value == 0
 }
 
-fn main() -> Result<(), io::Error> {
-    let f = File::open("2-input.txt")?;
-    let reader = BufReader::new(f);
-    let range_line = reader.lines().next().unwrap()?;
-    let mut ranges: Vec<_> = range_line.split(",").map(parse_range).collect();
-    ranges.sort_unstable();
+fn is_invalid(value: u128, rule: Repeats, radix: u32) -> bool {
+    let len = digit_count(value, radix);
+    match rule {
+        Repeats::Exactly(k) => len.is_multiple_of(k) && repeats_with_period(value, len / k, radix),
+        Repeats::AtLeastTwice => (1..len)
+            .filter(|x| len.is_multiple_of(*x))
+            .any(|j| repeats_with_period(value, j, radix)),
+    }
+}
 
-    let mut total: i64 = 0;
-    let mut last: i64 = 0;
-    for (low, high) in ranges {
-        let low = max(low, last);
-        last = max(high + 1, last);
-        total += (low..last).filter(|x| is_invalid(*x)).sum::<i64>();
+// Smallest block that the ID is a repetition of, with its repeat count.
+fn smallest_block(value: u128, radix: u32) -> Option<(u128, u32)> {
+    let len = digit_count(value, radix);
+    (1..len)
+        .filter(|p| len.is_multiple_of(*p) && repeats_with_period(value, *p, radix))
+        .map(|p| (value % u128::from(radix).pow(p), len / p))
+        .next()
+}
+
+fn periods(len: u32, rule: Repeats) -> Vec<u32> {
+    match rule {
+        Repeats::Exactly(k) if len.is_multiple_of(k) => vec![len / k],
+        Repeats::Exactly(_) => Vec::new(),
+        Repeats::AtLeastTwice => (1..len).filter(|p| len.is_multiple_of(*p)).collect(),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Tally {
+    count: u128,
+    sum: u128,
+}
+
+impl Tally {
+    fn checked_add(self, other: Tally) -> Option<Tally> {
+        Some(Tally {
+            count: self.count.checked_add(other.count)?,
+            sum: self.sum.checked_add(other.sum)?,
+        })
+    }
+
+    fn sub(self, other: Tally) -> Tally {
+        Tally {
+            count: self.count - other.count,
+            sum: self.sum - other.sum,
+        }
+    }
+}
+
+// The `len`-digit numbers in `low..=high` made of a `period`-digit block
+// repeated are the block times 1 0..01 0..01 ..., so they come from a
+// contiguous run of blocks. Returns the multiplier and that run, if any. A
+// multiplier that doesn't fit in a u128 means no such number fits either.
+fn block_run(
+    low: u128,
+    high: u128,
+    len: u32,
+    period: u32,
+    radix: u32,
+) -> Option<(u128, u128, u128)> {
+    let radix = u128::from(radix);
+    let split = radix.pow(period);
+    let multiplier =
+        (0..len / period).try_fold(0u128, |m, _| m.checked_mul(split)?.checked_add(1))?;
+    let block_low = max(radix.pow(period - 1), low.div_ceil(multiplier));
+    let block_high = min(split - 1, high / multiplier);
+    (block_low <= block_high).then_some((multiplier, block_low, block_high))
+}
+
+fn repeated_tally(low: u128, high: u128, len: u32, period: u32, radix: u32) -> Option<Tally> {
+    let Some((multiplier, block_low, block_high)) = block_run(low, high, len, period, radix) else {
+        return Some(Tally::default());
+    };
+    let (count, ends) = (block_high - block_low + 1, block_low + block_high);
+    let blocks = if count % 2 == 0 {
+        (count / 2).checked_mul(ends)?
+    } else {
+        count.checked_mul(ends / 2)?
+    };
+    Some(Tally {
+        count,
+        sum: multiplier.checked_mul(blocks)?,
+    })
+}
+
+fn invalid_tally(low: u128, high: u128, rule: Repeats, radix: u32) -> Option<Tally> {
+    let mut total = Tally::default();
+    for len in digit_count(low, radix)..=digit_count(high, radix) {
+        match rule {
+            Repeats::Exactly(k) => {
+                if len.is_multiple_of(k) {
+                    total = total.checked_add(repeated_tally(low, high, len, len / k, radix)?)?;
+                }
+            }
+            Repeats::AtLeastTwice => {
+                // Tallies of the numbers whose shortest repeating block has
+                // each length, so a number repeating at several periods
+                // counts once.
+                let mut primitive = vec![Tally::default(); len as usize];
+                for period in periods(len, rule) {
+                    let shorter = (1..period)
+                        .filter(|q| period.is_multiple_of(*q))
+                        .try_fold(Tally::default(), |t, q| {
+                            t.checked_add(primitive[q as usize])
+                        })?;
+                    primitive[period as usize] =
+                        repeated_tally(low, high, len, period, radix)?.sub(shorter);
+                    total = total.checked_add(primitive[period as usize])?;
+                }
+            }
+        }
+    }
+    Some(total)
+}
+
+fn invalid_ids(low: u128, high: u128, rule: Repeats, radix: u32) -> Vec<u128> {
+    let mut ids = Vec::new();
+    for len in digit_count(low, radix)..=digit_count(high, radix) {
+        for period in periods(len, rule) {
+            if let Some((multiplier, block_low, block_high)) =
+                block_run(low, high, len, period, radix)
+            {
+                ids.extend((block_low..=block_high).map(|b| b * multiplier));
+            }
+        }
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+fn range_tally(low: u128, high: u128, rule: &dyn Rule, opts: &Options) -> Option<Tally> {
+    if opts.scan {
+        scan_tally(rule, low, high, opts.radix)
+    } else {
+        rule.tally(low, high, opts.radix)
+    }
+}
+
+// Spans cut into pieces of roughly equal width, so one very wide span is
+// shared between workers as well as many narrow ones.
+fn chunks(spans: &[(u128, u128)], pieces: u128) -> Vec<(u128, u128)> {
+    let width = spans.iter().fold(0u128, |w, &(low, high)| {
+        w.saturating_add(high - low).saturating_add(1)
+    });
+    let step = max(1, width / pieces);
+    let mut chunks = Vec::new();
+    for &(low, high) in spans {
+        let mut start = low;
+        loop {
+            let end = min(start.saturating_add(step - 1), high);
+            chunks.push((start, end));
+            if end == high {
+                break;
+            }
+            start = end + 1;
+        }
+    }
+    chunks
+}
+
+// Workers pull chunks off a shared counter and results are summed back in
+// chunk order, so the outcome doesn't depend on scheduling.
+fn spans_tally(spans: &[(u128, u128)], rule: &dyn Rule, opts: &Options) -> Option<Tally> {
+    const CHUNKS_PER_THREAD: u128 = 16;
+    let chunks = chunks(spans, opts.threads as u128 * CHUNKS_PER_THREAD);
+    let next = AtomicUsize::new(0);
+    let mut results = vec![None; chunks.len()];
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..opts.threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&(low, high)) = chunks.get(i) else {
+                            break;
+                        };
+                        done.push((i, range_tally(low, high, rule, opts)));
+                    }
+                    done
+                })
+            })
+            .collect();
+        for worker in workers {
+            for (i, tally) in worker.join().unwrap() {
+                results[i] = tally;
+            }
+        }
+    });
+    results
+        .into_iter()
+        .try_fold(Tally::default(), |total, tally| total.checked_add(tally?))
+}
+
+struct Options {
+    rules: Vec<Box<dyn Rule>>,
+    scan: bool,
+    radix: u32,
+    report: bool,
+    list: bool,
+    explain: Option<Vec<String>>,
+    threads: usize,
+}
+
+fn parse_args() -> Result<Options, Box<dyn Error>> {
+    let mut opts = Options {
+        rules: Vec::new(),
+        scan: false,
+        radix: 10,
+        report: false,
+        list: false,
+        explain: None,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--rule" => opts.rules.push(parse_rule(&value()?)?),
+            "--radix" => opts.radix = value()?.parse()?,
+            "explain" if opts.explain.is_none() => opts.explain = Some(Vec::new()),
+            "--scan" => opts.scan = true,
+            "--threads" => opts.threads = value()?.parse()?,
+            "--report" => opts.report = true,
+            "--list" => {
+                opts.report = true;
+                opts.list = true;
+            }
+            _ => match &mut opts.explain {
+                Some(ids) if !arg.starts_with("--") => ids.push(arg),
+                _ => return Err(format!("Unknown argument {}", arg).into()),
+            },
+        }
+    }
+    if opts.rules.is_empty() {
+        opts.rules = vec![
+            Box::new(Repeats::Exactly(2)),
+            Box::new(Repeats::AtLeastTwice),
+        ];
+    }
+    if opts.explain.as_ref().is_some_and(Vec::is_empty) {
+        return Err("explain needs at least one ID".into());
+    }
+    if opts.threads == 0 {
+        return Err("Need at least one thread".into());
+    }
+    if !(2..=36).contains(&opts.radix) {
+        return Err("Radix must be between 2 and 36".into());
+    }
+    Ok(opts)
+}
+
+// Per-range breakdown in input order. Ranges are deduplicated the same way
+// the merge does it, walking them in sorted order behind a cursor, and a
+// range that overlaps others shows what is left of it after that.
+fn report(ranges: &[(u128, u128)], rule: &dyn Rule, opts: &Options) -> Result<(), Box<dyn Error>> {
+    let overflow = || format!("Invalid ID tally ({}) overflows u128", rule);
+    let mut order: Vec<_> = (0..ranges.len()).collect();
+    order.sort_unstable_by_key(|&i| ranges[i]);
+    let mut deduped = vec![None; ranges.len()];
+    let mut last: Option<u128> = None;
+    for i in order {
+        let (low, high) = ranges[i];
+        let low = last.map_or(low, |l| max(low, l.saturating_add(1)));
+        if last.is_none_or(|l| l < high) {
+            last = Some(high);
+        }
+        if low <= high {
+            deduped[i] = Some((low, high));
+        }
+    }
+
+    let id = |x: u128| format_id(x, opts.radix);
+    println!("Report ({}):", rule);
+    for (i, &(low, high)) in ranges.iter().enumerate() {
+        let tally = spans_tally(&[(low, high)], rule, opts).ok_or_else(overflow)?;
+        let overlaps: Vec<_> = (0..ranges.len())
+            .filter(|&j| j != i && ranges[j].0 <= high && low <= ranges[j].1)
+            .map(|j| format!("#{}", j + 1))
+            .collect();
+        print!(
+            "#{} {}-{}: {} invalid, sum {}",
+            i + 1,
+            id(low),
+            id(high),
+            tally.count,
+            id(tally.sum)
+        );
+        if !overlaps.is_empty() {
+            let kept = match deduped[i] {
+                Some((low, high)) => {
+                    spans_tally(&[(low, high)], rule, opts).ok_or_else(overflow)?
+                }
+                None => Tally::default(),
+            };
+            print!(
+                " [overlaps {}; after dedup {} invalid, sum {}]",
+                overlaps.join(" "),
+                kept.count,
+                id(kept.sum)
+            );
+        }
+        println!();
+        if opts.list {
+            for x in rule.ids(low, high, opts.radix) {
+                match smallest_block(x, opts.radix) {
+                    Some((block, repeats)) => {
+                        println!("    {} (block {} x{})", id(x), id(block), repeats)
+                    }
+                    None => println!("    {}", id(x)),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn explain(ids: &[String], opts: &Options) -> Result<(), Box<dyn Error>> {
+    for s in ids {
+        let value = parse_id(s, opts.radix).map_err(|e| format!("Bad ID {:?}: {}", s, e))?;
+        println!("{}", format_id(value, opts.radix));
+        for rule in &opts.rules {
+            let verdict = if rule.is_invalid(value, opts.radix) {
+                "invalid"
+            } else {
+                "valid"
+            };
+            println!("    {}: {}", rule, verdict);
+        }
+        match smallest_block(value, opts.radix) {
+            Some((block, repeats)) => println!(
+                "    shortest block {} repeated {} times",
+                format_id(block, opts.radix),
+                repeats
+            ),
+            None => println!(
+                "    no divisor of length {} gives a repeating block",
+                digit_count(value, opts.radix)
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let opts = parse_args()?;
+    if let Some(ids) = &opts.explain {
+        return explain(ids, &opts);
+    }
+    let ranges = parse_ranges(&fs::read_to_string("2-input.txt")?, opts.radix)?;
+    let mut sorted = ranges.clone();
+    sorted.sort_unstable();
+
+    let mut merged: Vec<(u128, u128)> = Vec::new();
+    for (low, high) in sorted {
+        match merged.last_mut() {
+            Some((_, last)) if low <= last.saturating_add(1) => *last = max(*last, high),
+            _ => merged.push((low, high)),
+        }
+    }
+
+    for rule in &opts.rules {
+        let rule = rule.as_ref();
+        if opts.report {
+            report(&ranges, rule, &opts)?;
+        }
+        let total = spans_tally(&merged, rule, &opts)
+            .ok_or_else(|| format!("Sum of invalid IDs ({}) overflows u128", rule))?;
+        println!("Result ({}): {}", rule, format_id(total.sum, opts.radix));
     }
-    println!("Result {}", total);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_tally_matches_scan() {
+        let rules = [
+            Repeats::Exactly(2),
+            Repeats::Exactly(3),
+            Repeats::AtLeastTwice,
+        ];
+        // Each range crosses at least one change in digit length.
+        let ranges = [(1, 1_500), (95, 1_012), (9_990, 101_000), (0, 70)];
+        for radix in [2, 10, 16] {
+            for rule in rules {
+                for (low, high) in ranges {
+                    assert_eq!(
+                        invalid_tally(low, high, rule, radix),
+                        scan_tally(&rule, low, high, radix),
+                        "{} in base {} over {}-{}",
+                        rule,
+                        radix,
+                        low,
+                        high
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parse_id_prefixes_and_signs() {
+        assert_eq!(parse_id("0x1F", 10), Ok(31));
+        assert_eq!(parse_id("0X1f", 10), Ok(31));
+        assert_eq!(parse_id("0B101", 10), Ok(5));
+        assert_eq!(parse_id("0b1", 16), Ok(0xb1));
+        assert_eq!(parse_id("+5", 10), Err(RangeErrorKind::BadDigit));
+        assert_eq!(parse_id("0x+5", 10), Err(RangeErrorKind::BadDigit));
+        assert_eq!(parse_range("+5-+9", 10), Err(RangeErrorKind::BadDigit));
+    }
+}
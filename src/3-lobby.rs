@@ -1,10 +1,176 @@
 use std::{
+    env,
+    error::Error,
+    fmt,
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{BufRead, BufReader},
+    ops::AddAssign,
+    process,
+    str::FromStr,
 };
 
-fn max_joltage(s: &str) -> u64 {
-    let values: Vec<_> = s.chars().map(|x| x.to_digit(10).unwrap()).collect();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BankErrorKind {
+    BadDigit { column: usize, found: char },
+    TooShort { len: usize, k: usize },
+}
+
+impl fmt::Display for BankErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BankErrorKind::BadDigit { column, found } => {
+                write!(f, "column {}: {:?} is not a battery digit", column, found)
+            }
+            BankErrorKind::TooShort { len, k } => {
+                write!(f, "bank has {} batteries, need at least {}", len, k)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BankError {
+    line: usize,
+    kind: BankErrorKind,
+}
+
+impl fmt::Display for BankError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for BankError {}
+
+fn parse_bank(s: &str, k: usize) -> Result<Vec<u32>, BankErrorKind> {
+    let values = s
+        .chars()
+        .enumerate()
+        .map(|(i, x)| {
+            x.to_digit(10).ok_or(BankErrorKind::BadDigit {
+                column: i + 1,
+                found: x,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if values.len() < k {
+        return Err(BankErrorKind::TooShort {
+            len: values.len(),
+            k,
+        });
+    }
+    Ok(values)
+}
+
+// Non-negative decimal integer of any length, least significant digit
+// first with no trailing zeros. Joltages are only ever built from digits and
+// summed, so that's all it supports.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Decimal(Vec<u8>);
+
+impl Decimal {
+    fn from_digits(digits: impl DoubleEndedIterator<Item = u32>) -> Self {
+        let mut digits: Vec<_> = digits.rev().map(|d| d as u8).collect();
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Decimal(digits)
+    }
+}
+
+impl AddAssign<&Decimal> for Decimal {
+    fn add_assign(&mut self, other: &Decimal) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        let mut carry = 0;
+        for i in 0..self.0.len() {
+            let sum = self.0[i] + other.0.get(i).copied().unwrap_or(0) + carry;
+            self.0[i] = sum % 10;
+            carry = sum / 10;
+            if carry == 0 && i >= other.0.len() {
+                break;
+            }
+        }
+        if carry > 0 {
+            self.0.push(carry);
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("0");
+        }
+        let s: String = self.0.iter().rev().map(|&d| char::from(b'0' + d)).collect();
+        f.write_str(&s)
+    }
+}
+
+#[derive(Debug)]
+struct Selection {
+    joltage: Decimal,
+    positions: Vec<usize>,
+}
+
+// Largest k-digit subsequence in one pass: a digit knocks smaller ones off
+// the stack while there are still digits to spare.
+fn max_joltage(s: &str, k: usize) -> Result<Selection, BankErrorKind> {
+    let values = parse_bank(s, k)?;
+    let mut spare = values.len() - k;
+    let mut stack: Vec<usize> = Vec::with_capacity(values.len());
+    for (i, &x) in values.iter().enumerate() {
+        while spare > 0 && stack.last().is_some_and(|&top| values[top] < x) {
+            stack.pop();
+            spare -= 1;
+        }
+        stack.push(i);
+    }
+    stack.truncate(k);
+    Ok(Selection {
+        joltage: Decimal::from_digits(stack.iter().map(|&i| values[i])),
+        positions: stack,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Highlight {
+    Brackets,
+    Ansi,
+}
+
+impl FromStr for Highlight {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "brackets" => Ok(Highlight::Brackets),
+            "ansi" => Ok(Highlight::Ansi),
+            _ => Err(format!("Unknown highlight style {}", s)),
+        }
+    }
+}
+
+fn highlight(bank: &str, positions: &[usize], style: Highlight) -> String {
+    let mut chosen = positions.iter().peekable();
+    let mut out = String::new();
+    for (i, c) in bank.chars().enumerate() {
+        if chosen.next_if(|&&p| p == i).is_some() {
+            match style {
+                Highlight::Brackets => out.extend(['[', c, ']']),
+                Highlight::Ansi => out.push_str(&format!("\x1b[1;32m{}\x1b[0m", c)),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// Original window-rescanning version, O(n * k). Kept to check the stack
+// version against.
+fn max_joltage_reference(s: &str, k: usize) -> Result<Decimal, BankErrorKind> {
+    let values = parse_bank(s, k)?;
 
     let max_idx = |xs: &[u32]| {
         xs.iter()
@@ -12,18 +178,142 @@ fn max_joltage(s: &str) -> u64 {
             .fold(0, |max, (i, &x)| if x > xs[max] { i } else { max })
     };
 
-    let (_, total) = (0..12).rev().fold((0, 0u64), |(start_idx, total), i| {
-        let next_idx = start_idx + max_idx(&values[start_idx..values.len() - i]);
-        (next_idx + 1, 10 * total + values[next_idx] as u64)
-    });
-    total
+    let (_, digits) = (0..k)
+        .rev()
+        .fold((0, Vec::new()), |(start_idx, mut digits), i| {
+            let next_idx = start_idx + max_idx(&values[start_idx..values.len() - i]);
+            digits.push(values[next_idx]);
+            (next_idx + 1, digits)
+        });
+    Ok(Decimal::from_digits(digits.into_iter()))
+}
+
+struct Options {
+    ks: Vec<usize>,
+    verify: bool,
+    show: Option<Highlight>,
 }
 
-fn main() -> Result<(), io::Error> {
+fn parse_args() -> Result<Options, Box<dyn Error>> {
+    let mut ks = Vec::new();
+    let mut verify = false;
+    let mut show = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--verify" => verify = true,
+            "--batteries" => ks.push(value()?.parse()?),
+            "--show" => show = Some(value()?.parse()?),
+            _ => return Err(format!("Unknown argument {}", arg).into()),
+        }
+    }
+    if ks.is_empty() {
+        ks = vec![2, 12];
+    }
+    Ok(Options { ks, verify, show })
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let opts = parse_args()?;
     let f = File::open("3-input.txt")?;
     let reader = BufReader::new(f);
+    let banks: Vec<_> = reader.lines().collect::<Result<_, _>>()?;
 
-    let total: u64 = reader.lines().map(|l| max_joltage(&l.unwrap())).sum();
-    println!("Result: {}", total);
+    for k in opts.ks {
+        let mut total = Decimal::default();
+        for (i, bank) in banks.iter().enumerate() {
+            let error = |kind| BankError { line: i + 1, kind };
+            let selection = max_joltage(bank, k).map_err(error)?;
+            let joltage = &selection.joltage;
+            if let Some(style) = opts.show {
+                println!(
+                    "{:>5}: {} = {}",
+                    i + 1,
+                    highlight(bank, &selection.positions, style),
+                    joltage
+                );
+            }
+            if opts.verify {
+                let expected = max_joltage_reference(bank, k).map_err(error)?;
+                if *joltage != expected {
+                    return Err(format!(
+                        "line {}: stack gives {}, reference gives {}",
+                        i + 1,
+                        joltage,
+                        expected
+                    )
+                    .into());
+                }
+            }
+            total += joltage;
+        }
+        println!("Result ({} batteries): {}", k, total);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Small xorshift generator, so the banks are the same on every run.
+    fn banks() -> Vec<String> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut digit = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            char::from_digit((state % 9 + 1) as u32, 10).unwrap()
+        };
+        [1, 2, 5, 12, 20, 37, 100, 500]
+            .into_iter()
+            .flat_map(|len| (0..4).map(move |_| len))
+            .map(|len| (0..len).map(|_| digit()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn stack_matches_reference() {
+        for bank in banks() {
+            for k in [1, 2, 12, 19, 20, 40, bank.len()] {
+                if k > bank.len() {
+                    continue;
+                }
+                assert_eq!(
+                    max_joltage(&bank, k).unwrap().joltage,
+                    max_joltage_reference(&bank, k).unwrap(),
+                    "k = {} on {}",
+                    k,
+                    bank
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bad_digit_is_an_error() {
+        let bad = BankErrorKind::BadDigit {
+            column: 3,
+            found: 'a',
+        };
+        assert_eq!(max_joltage("12a4", 2).unwrap_err(), bad);
+        assert_eq!(max_joltage_reference("12a4", 2).unwrap_err(), bad);
+    }
+
+    #[test]
+    fn short_bank_is_an_error() {
+        assert!(max_joltage("123", 4).is_err());
+        assert!(max_joltage_reference("123", 4).is_err());
+    }
+}